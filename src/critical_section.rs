@@ -0,0 +1,49 @@
+//! `critical_section` crate implementation for this chip
+
+use crate::interrupt;
+
+/// Number of LX6 cores on the ESP32, each needing its own nesting stack
+const CORES: usize = 2;
+
+/// How deeply `acquire`/`release` pairs may nest on one core
+///
+/// `critical_section`'s default `RawRestoreState` is a `u8`, which is too
+/// narrow to carry the full saved `PS`/`INTENABLE` state directly, so that
+/// state is kept here instead and only an index into it is handed out as
+/// the token. 8 fits comfortably in a `u8` and is far deeper than this
+/// crate's own code ever nests critical sections.
+const MAX_DEPTH: usize = 8;
+
+/// Interrupt state saved by `acquire`, per core and nesting depth
+static mut SAVED: [[Option<interrupt::State>; MAX_DEPTH]; CORES] = [[None; MAX_DEPTH]; CORES];
+
+/// Current nesting depth of `acquire`/`release` pairs, per core
+static mut DEPTH: [usize; CORES] = [0; CORES];
+
+struct XtensaLx6CriticalSection;
+
+critical_section::set_impl!(XtensaLx6CriticalSection);
+
+unsafe impl critical_section::Impl for XtensaLx6CriticalSection {
+    unsafe fn acquire() -> critical_section::RawRestoreState {
+        let state = interrupt::disable();
+
+        let core = crate::get_processor_id() as usize;
+        let depth = DEPTH[core];
+        assert!(depth < MAX_DEPTH, "critical sections nested too deeply");
+        SAVED[core][depth] = Some(state);
+        DEPTH[core] = depth + 1;
+
+        depth as critical_section::RawRestoreState
+    }
+
+    unsafe fn release(token: critical_section::RawRestoreState) {
+        let core = crate::get_processor_id() as usize;
+        let depth = token as usize;
+        debug_assert_eq!(depth + 1, DEPTH[core], "critical sections released out of order");
+
+        let state = SAVED[core][depth].take().expect("double release");
+        DEPTH[core] = depth;
+        interrupt::enable(state);
+    }
+}
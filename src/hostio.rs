@@ -0,0 +1,63 @@
+//! Debugger host I/O over the Xtensa OCD/TRAX `simcall` trap
+//!
+//! Writes are no-ops when no debugger is attached.
+
+use core::fmt;
+
+use crate::is_debugger_attached;
+
+/// Simcall requesting the host write `len` bytes starting at `ptr`
+const SYS_WRITE: u32 = 0x05;
+
+/// Issue a `simcall` asking the attached debugger to write `len` bytes from
+/// `ptr` to its host console
+///
+/// # Safety
+///
+/// Only sound to call while a debugger is attached and driving the OCD/TRAX
+/// channel; the caller must check [`is_debugger_attached`] first.
+#[inline]
+unsafe fn simcall_write(ptr: *const u8, len: usize) {
+    // The Xtensa `simcall` trap reads its arguments out of fixed registers:
+    // `a2` holds the syscall number, `a3`/`a4` the pointer and length of the
+    // byte block OpenOCD copies out.
+    llvm_asm!("simcall"
+        :: "{a2}"(SYS_WRITE), "{a3}"(ptr), "{a4}"(len)
+        : "memory"
+        : "volatile");
+}
+
+/// A [`core::fmt::Write`] sink that forwards to an attached debugger's host
+/// console
+///
+/// Writes are silently dropped when no debugger is attached.
+pub struct HostIo;
+
+impl fmt::Write for HostIo {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if is_debugger_attached() {
+            unsafe { simcall_write(s.as_ptr(), s.len()) };
+        }
+        Ok(())
+    }
+}
+
+/// Write pre-formatted arguments to the attached debugger, if any
+///
+/// Used by [`hprintln!`]; prefer the macro in application code.
+pub fn write_fmt(args: fmt::Arguments) {
+    use fmt::Write;
+    let _ = HostIo.write_fmt(args);
+}
+
+/// Print to the attached debugger's host console, if any, with a trailing
+/// newline
+#[macro_export]
+macro_rules! hprintln {
+    () => {
+        $crate::hostio::write_fmt(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::hostio::write_fmt(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
@@ -1,8 +1,13 @@
 #![no_std]
 #![feature(llvm_asm)]
 
+#[cfg(feature = "critical-section")]
+mod critical_section;
+#[cfg(feature = "hostio")]
+pub mod hostio;
 pub mod interrupt;
 pub mod mutex;
+pub mod stack;
 pub mod timer;
 
 #[macro_use]
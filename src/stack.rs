@@ -0,0 +1,96 @@
+//! Stack high-water-mark measurement and overflow guard
+
+use crate::get_stack_pointer;
+
+/// Number of LX6 cores on the ESP32, each with its own physical stack
+const CORES: usize = 2;
+
+/// Word written across the unused stack region by [`paint`]
+const SENTINEL: u32 = 0xA5A5_A5A5;
+
+/// Lowest address of the painted stack, per core (the end closest to
+/// overflowing)
+static mut STACK_LIMIT: [*const u32; CORES] = [core::ptr::null(); CORES];
+
+/// Highest address of the painted stack, per core (one past the last usable
+/// word)
+static mut STACK_TOP: [*const u32; CORES] = [core::ptr::null(); CORES];
+
+/// Fill the unused region of the current core's stack with a known sentinel
+/// word
+///
+/// Call this once per core, as early as possible after the stack pointer is
+/// set up.
+///
+/// # Safety
+///
+/// `limit` and `top` must describe the calling core's actual stack, with
+/// `limit <= get_stack_pointer() <= top`, and no other code may be
+/// concurrently using this stack.
+pub unsafe fn paint(limit: *const u32, top: *const u32) {
+    let core = crate::get_processor_id() as usize;
+    STACK_LIMIT[core] = limit;
+    STACK_TOP[core] = top;
+
+    let sp = get_stack_pointer();
+    let mut p = limit as *mut u32;
+    while (p as *const u32) < sp {
+        p.write_volatile(SENTINEL);
+        p = p.add(1);
+    }
+}
+
+/// Report the maximum stack usage, in bytes, observed on the current core
+/// since the last call to [`paint`] on that core
+pub fn stack_high_water_mark() -> usize {
+    unsafe {
+        let core = crate::get_processor_id() as usize;
+        let top = STACK_TOP[core];
+        let mut p = STACK_LIMIT[core];
+        while p < top && p.read_volatile() == SENTINEL {
+            p = p.add(1);
+        }
+        (top as usize) - (p as usize)
+    }
+}
+
+/// Bytes of stack left on the current core between its stack pointer and its
+/// painted limit
+pub fn stack_remaining() -> usize {
+    unsafe {
+        let core = crate::get_processor_id() as usize;
+        (get_stack_pointer() as usize).saturating_sub(STACK_LIMIT[core] as usize)
+    }
+}
+
+/// Value written by [`arm_canary`] and checked by [`check_stack_overflow`]
+const CANARY: u32 = 0xDEAD_BEEF;
+
+/// Address of the canary word armed by [`arm_canary`], per core
+static mut CANARY_ADDR: [*mut u32; CORES] = [core::ptr::null_mut(); CORES];
+
+/// Arm a canary word just past the current core's stack limit
+///
+/// # Safety
+///
+/// `addr` must point at a word that is not otherwise used by the
+/// application for the lifetime of the stack.
+pub unsafe fn arm_canary(addr: *mut u32) {
+    let core = crate::get_processor_id() as usize;
+    CANARY_ADDR[core] = addr;
+    addr.write_volatile(CANARY);
+}
+
+/// Trap into the debugger if the canary armed by [`arm_canary`] on the
+/// current core has been clobbered
+///
+/// Does nothing if no canary has been armed on this core.
+pub fn check_stack_overflow() {
+    unsafe {
+        let core = crate::get_processor_id() as usize;
+        let addr = CANARY_ADDR[core];
+        if !addr.is_null() && addr.read_volatile() != CANARY {
+            crate::debug_break();
+        }
+    }
+}